@@ -22,83 +22,52 @@
 
 mod common;
 
-use common::to_range;
+use common::{generate_random_npos_result, to_range, AccountId, Balance};
 use honggfuzz::fuzz;
 use sp_npos_elections::{
-	assignment_ratio_to_staked_normalized, build_support_map, to_without_backing, seq_phragmen,
-	ElectionResult, VoteWeight, evaluate_support, is_score_better,
+	assignment_ratio_to_staked_normalized, balance, build_support_map, to_without_backing,
+	seq_phragmen, evaluate_support, is_score_better, ExtendedBalance, SaturatingCurrencyToVote,
+	StakedAssignment, VoteWeight,
 };
-use sp_std::collections::btree_map::BTreeMap;
 use sp_runtime::Perbill;
-use rand::{self, Rng, SeedableRng, RngCore};
-
-type AccountId = u64;
-
-fn generate_random_npos_result(
-	voter_count: u64,
-	target_count: u64,
-	to_elect: usize,
-	mut rng: impl RngCore,
-) -> (
-	ElectionResult<AccountId, Perbill>,
-	Vec<AccountId>,
-	Vec<(AccountId, VoteWeight, Vec<AccountId>)>,
-	BTreeMap<AccountId, VoteWeight>,
-) {
-	let prefix = 100_000;
-	// Note, it is important that stakes are always bigger than ed.
-	let base_stake: u64 = 1_000_000_000;
-	let ed: u64 = base_stake;
-
-	let mut candidates = Vec::with_capacity(target_count as usize);
-	let mut stake_of: BTreeMap<AccountId, VoteWeight> = BTreeMap::new();
-
-	(1..=target_count).for_each(|acc| {
-		candidates.push(acc);
-		let stake_var = rng.gen_range(ed, 100 * ed);
-		stake_of.insert(acc, base_stake + stake_var);
-	});
-
-	let mut voters = Vec::with_capacity(voter_count as usize);
-	(prefix ..= (prefix + voter_count)).for_each(|acc| {
-		let edge_per_this_voter = rng.gen_range(1, candidates.len());
-		// all possible targets
-		let mut all_targets = candidates.clone();
-		// we remove and pop into `targets` `edge_per_this_voter` times.
-		let targets = (0..edge_per_this_voter).map(|_| {
-			let upper = all_targets.len() - 1;
-			let idx = rng.gen_range(0, upper);
-			all_targets.remove(idx)
+use rand::{self, SeedableRng};
+
+/// Build a starting assignment that no solver in this crate would ever emit: split each voter's
+/// stake evenly across every target it approves of, rather than through any election logic. This
+/// lets the fuzzer exercise [`balance`] directly, instead of only ever on [`seq_phragmen`]'s own
+/// output.
+fn arbitrary_assignments(
+	voters: &[(AccountId, VoteWeight, Vec<AccountId>)],
+) -> Vec<StakedAssignment<AccountId>> {
+	voters
+		.iter()
+		.filter(|(_, _, targets)| !targets.is_empty())
+		.map(|(who, stake, targets)| {
+			let share = *stake as ExtendedBalance / targets.len() as ExtendedBalance;
+			let mut distribution =
+				targets.iter().map(|t| (t.clone(), share)).collect::<Vec<_>>();
+
+			// correct the last edge so the distribution sums to exactly `stake`, the same way the
+			// rest of this crate normalizes rounding error on the last edge.
+			let allocated: ExtendedBalance = distribution.iter().map(|(_, w)| *w).sum();
+			if let Some((_, last)) = distribution.last_mut() {
+				*last = last.saturating_add((*stake as ExtendedBalance).saturating_sub(allocated));
+			}
+
+			StakedAssignment { who: who.clone(), distribution }
 		})
-		.collect::<Vec<AccountId>>();
-
-		let stake_var = rng.gen_range(ed, 100 * ed) ;
-		let stake = base_stake + stake_var;
-		stake_of.insert(acc, stake);
-		voters.push((acc, stake, targets));
-	});
-
-	(
-		seq_phragmen::<AccountId, sp_runtime::Perbill>(
-			to_elect,
-			candidates.clone(),
-			voters.clone(),
-			None,
-		).unwrap(),
-		candidates,
-		voters,
-		stake_of,
-	)
+		.collect::<Vec<_>>()
 }
 
 fn main() {
 	loop {
-		fuzz!(|data: (usize, usize, usize, usize, u64)| {
+		fuzz!(|data: (usize, usize, usize, usize, usize, u64)| {
 			let (
 				mut target_count,
 				mut voter_count,
 				mut iterations,
 				mut to_elect,
+				issuance_scale_seed,
 				seed,
 			) = data;
 			let rng = rand::rngs::SmallRng::seed_from_u64(seed);
@@ -106,29 +75,53 @@ fn main() {
 			voter_count = to_range(voter_count, 50, 1000);
 			iterations = to_range(iterations, 1, 50);
 			to_elect = to_range(to_elect, 25, target_count);
+			// scale issuance from well within `u64` to far past it; most of this range pushes
+			// `total_issuance` past `u64::MAX`.
+			let issuance_scale = to_range(issuance_scale_seed, 1, 1_000_000_000_000) as u128;
 
 			println!(
-				"++ [voter_count: {} / target_count:{} / to_elect:{} / iterations:{}]",
-				voter_count, target_count, to_elect, iterations,
+				"++ [voter_count: {} / target_count:{} / to_elect:{} / iterations:{} / issuance_scale:{}]",
+				voter_count, target_count, to_elect, iterations, issuance_scale,
 			);
-			let (
-				unbalanced,
-				candidates,
-				voters,
-				stake_of_tree,
-			) = generate_random_npos_result(
+			let (candidates, voters, balance_of_tree, total_issuance) = generate_random_npos_result(
 				voter_count as u64,
 				target_count as u64,
-				to_elect,
+				issuance_scale,
 				rng,
 			);
 
-			let stake_of = |who: &AccountId| -> VoteWeight {
-				*stake_of_tree.get(who).unwrap()
+			let balance_of = |who: &AccountId| -> Balance {
+				*balance_of_tree.get(who).unwrap()
 			};
 
+			// balance an arbitrary, non-solver-originated assignment directly, rather than only
+			// ever the output of `seq_phragmen`.
+			let mut arbitrary = arbitrary_assignments(&voters);
+			let (mut arbitrary_support, _) = build_support_map(candidates.as_ref(), arbitrary.as_ref());
+			let arbitrary_score = evaluate_support(&arbitrary_support);
+
+			balance(&mut arbitrary, &mut arbitrary_support, iterations, 0);
+			let balanced_arbitrary_score = evaluate_support(&arbitrary_support);
+
+			assert!(
+				balanced_arbitrary_score[0] >= arbitrary_score[0] &&
+				balanced_arbitrary_score[1] == arbitrary_score[1] &&
+				balanced_arbitrary_score[2] <= arbitrary_score[2]
+			);
+
+			let unbalanced = seq_phragmen::<AccountId, sp_runtime::Perbill>(
+				to_elect,
+				candidates.clone(),
+				voters.clone(),
+				None,
+			).unwrap();
+
 			let unbalanced_score = {
-				let staked = assignment_ratio_to_staked_normalized(unbalanced.assignments.clone(), &stake_of).unwrap();
+				let staked = assignment_ratio_to_staked_normalized::<_, _, _, SaturatingCurrencyToVote>(
+					unbalanced.assignments.clone(),
+					total_issuance,
+					&balance_of,
+				).unwrap();
 				let winners = to_without_backing(unbalanced.winners);
 				let support = build_support_map(winners.as_ref(), staked.as_ref()).0;
 
@@ -148,7 +141,11 @@ fn main() {
 			).unwrap();
 
 			let balanced_score = {
-				let staked = assignment_ratio_to_staked_normalized(balanced.assignments.clone(), &stake_of).unwrap();
+				let staked = assignment_ratio_to_staked_normalized::<_, _, _, SaturatingCurrencyToVote>(
+					balanced.assignments.clone(),
+					total_issuance,
+					&balance_of,
+				).unwrap();
 				let winners = to_without_backing(balanced.winners);
 				let support = build_support_map(winners.as_ref(), staked.as_ref()).0;
 				evaluate_support(&support)
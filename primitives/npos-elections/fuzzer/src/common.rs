@@ -0,0 +1,110 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2020 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Small helpers shared between the fuzz targets in this crate.
+
+use rand::{Rng, RngCore};
+use sp_npos_elections::{CurrencyToVote, SaturatingCurrencyToVote, VoteWeight};
+use sp_std::collections::btree_map::BTreeMap;
+
+/// Fold `input` into the inclusive range `[min, max]`.
+///
+/// This is used to turn the arbitrary integers that honggfuzz hands us into parameters (voter
+/// count, target count, iteration count, ...) that are actually useful to fuzz with.
+pub fn to_range(input: usize, min: usize, max: usize) -> usize {
+	let width = max.saturating_sub(min).saturating_add(1);
+	if width == 0 {
+		min
+	} else {
+		min + (input % width)
+	}
+}
+
+pub type AccountId = u64;
+pub type Balance = u128;
+
+/// Generate a random universe of `candidates` and `voters` with `Balance`-denominated stakes,
+/// wide enough to exercise [`SaturatingCurrencyToVote`].
+///
+/// Balances are scaled up by `issuance_scale`: every account holds between `2 * base_stake` and
+/// `101 * base_stake`, with `base_stake = 1_000_000_000 * issuance_scale`, so a large enough
+/// `issuance_scale` pushes `total_issuance` past `u64::MAX`, exercising
+/// `SaturatingCurrencyToVote`'s scaling path.
+///
+/// Returns the candidates, the voters (with their stake already converted into `VoteWeight` via
+/// [`SaturatingCurrencyToVote`]), every account's raw `Balance`, and the `total_issuance` they sum
+/// to.
+pub fn generate_random_npos_result(
+	voter_count: u64,
+	target_count: u64,
+	issuance_scale: Balance,
+	mut rng: impl RngCore,
+) -> (
+	Vec<AccountId>,
+	Vec<(AccountId, VoteWeight, Vec<AccountId>)>,
+	BTreeMap<AccountId, Balance>,
+	Balance,
+) {
+	let prefix = 100_000;
+	// Note, it is important that stakes are always bigger than ed.
+	let base_stake: Balance = 1_000_000_000 * issuance_scale;
+	let ed: Balance = base_stake;
+
+	let mut candidates = Vec::with_capacity(target_count as usize);
+	let mut balance_of: BTreeMap<AccountId, Balance> = BTreeMap::new();
+	let mut total_issuance: Balance = 0;
+
+	(1..=target_count).for_each(|acc| {
+		candidates.push(acc);
+		let stake_var = rng.gen_range(ed, 100 * ed);
+		let balance = base_stake + stake_var;
+		total_issuance += balance;
+		balance_of.insert(acc, balance);
+	});
+
+	let mut voters = Vec::with_capacity(voter_count as usize);
+	(prefix..=(prefix + voter_count)).for_each(|acc| {
+		let edge_per_this_voter = rng.gen_range(1, candidates.len());
+		// all possible targets
+		let mut all_targets = candidates.clone();
+		// we remove and pop into `targets` `edge_per_this_voter` times.
+		let targets = (0..edge_per_this_voter)
+			.map(|_| {
+				let upper = all_targets.len() - 1;
+				let idx = rng.gen_range(0, upper);
+				all_targets.remove(idx)
+			})
+			.collect::<Vec<AccountId>>();
+
+		let stake_var = rng.gen_range(ed, 100 * ed);
+		let balance = base_stake + stake_var;
+		total_issuance += balance;
+		balance_of.insert(acc, balance);
+
+		voters.push((acc, balance, targets));
+	});
+
+	// only now that `total_issuance` is final can balances be converted into vote weights.
+	let voters = voters
+		.into_iter()
+		.map(|(who, balance, targets)| {
+			(who, SaturatingCurrencyToVote::to_vote(balance, total_issuance), targets)
+		})
+		.collect::<Vec<_>>();
+
+	(candidates, voters, balance_of, total_issuance)
+}
@@ -0,0 +1,172 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2020 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Fuzzing for `verify_and_score`.
+//!
+//! A clean solution must be accepted and reproduce exactly the score that `evaluate_support`
+//! would compute directly. A solution deliberately corrupted in one of a few specific ways must be
+//! rejected with precisely the matching [`FeasibilityError`].
+
+mod common;
+
+use common::{generate_random_npos_result, to_range, AccountId, Balance};
+use honggfuzz::fuzz;
+use sp_npos_elections::{
+	assignment_ratio_to_staked_normalized, build_support_map, evaluate_support, seq_phragmen,
+	to_without_backing, verify_and_score, FeasibilityError, SaturatingCurrencyToVote,
+};
+use sp_runtime::Perbill;
+use rand::{self, SeedableRng};
+
+/// The different ways this fuzz target deliberately corrupts an otherwise-valid solution.
+#[derive(Debug)]
+enum Corruption {
+	/// Leave the solution untouched.
+	None,
+	/// Drop a winner, so `winners.len() != desired_winners`.
+	WrongWinnerCount,
+	/// Point one voter's first edge at an account that isn't a winner.
+	NonWinnerBacked,
+	/// Duplicate one voter's first edge.
+	DuplicateTarget,
+	/// Make one voter vote for itself.
+	SelfVote,
+}
+
+impl Corruption {
+	fn from_seed(seed: usize) -> Self {
+		match to_range(seed, 0, 4) {
+			0 => Corruption::None,
+			1 => Corruption::WrongWinnerCount,
+			2 => Corruption::NonWinnerBacked,
+			3 => Corruption::DuplicateTarget,
+			_ => Corruption::SelfVote,
+		}
+	}
+}
+
+fn main() {
+	loop {
+		fuzz!(|data: (usize, usize, usize, usize, usize, u64)| {
+			let (
+				mut target_count,
+				mut voter_count,
+				mut to_elect,
+				issuance_scale_seed,
+				corruption_seed,
+				seed,
+			) = data;
+			let rng = rand::rngs::SmallRng::seed_from_u64(seed);
+			target_count = to_range(target_count, 50, 2000);
+			voter_count = to_range(voter_count, 50, 1000);
+			to_elect = to_range(to_elect, 25, target_count);
+			// scale issuance from well within `u64` to far past it; most of this range pushes
+			// `total_issuance` past `u64::MAX`.
+			let issuance_scale = to_range(issuance_scale_seed, 1, 1_000_000_000_000) as Balance;
+			let corruption = Corruption::from_seed(corruption_seed);
+
+			println!(
+				"++ [voter_count: {} / target_count: {} / to_elect: {} / issuance_scale: {} / corruption: {:?}]",
+				voter_count, target_count, to_elect, issuance_scale, corruption,
+			);
+
+			let (candidates, voters, balance_of_tree, total_issuance) = generate_random_npos_result(
+				voter_count as u64,
+				target_count as u64,
+				issuance_scale,
+				rng,
+			);
+			let balance_of = |who: &AccountId| -> Balance { *balance_of_tree.get(who).unwrap() };
+
+			let result = seq_phragmen::<AccountId, Perbill>(to_elect, candidates, voters, None).unwrap();
+			let mut winners = to_without_backing(result.winners);
+			let mut assignments = result.assignments;
+			let mut desired_winners = to_elect;
+
+			let expect_error = match corruption {
+				Corruption::None => None,
+				Corruption::WrongWinnerCount => {
+					winners.pop();
+					Some(FeasibilityError::WrongWinnerCount)
+				},
+				Corruption::NonWinnerBacked => {
+					if let Some(assignment) = assignments.iter_mut().find(|a| !a.distribution.is_empty()) {
+						// `0` is never a candidate in `generate_random_npos_result`.
+						assignment.distribution[0].0 = 0;
+						Some(FeasibilityError::NonWinnerBacked)
+					} else {
+						None
+					}
+				},
+				Corruption::DuplicateTarget => {
+					if let Some(assignment) =
+						assignments.iter_mut().find(|a| a.distribution.len() >= 2)
+					{
+						let duplicate = assignment.distribution[0].clone();
+						assignment.distribution[1] = duplicate;
+						Some(FeasibilityError::DuplicateTarget)
+					} else {
+						None
+					}
+				},
+				Corruption::SelfVote => {
+					if let Some(assignment) = assignments.iter_mut().find(|a| !a.distribution.is_empty()) {
+						let who = assignment.who;
+						assignment.distribution[0].0 = who;
+						Some(FeasibilityError::SelfVote)
+					} else {
+						None
+					}
+				},
+			};
+
+			// `WrongWinnerCount` is checked against `desired_winners`, not `winners.len()`, so it
+			// stays `to_elect` in every case, including the one deliberately shrinking `winners`:
+			// only there does `winners.len()` fall short of it.
+			if matches!(corruption, Corruption::WrongWinnerCount) {
+				desired_winners = to_elect;
+			}
+
+			let verified = verify_and_score::<_, _, _, SaturatingCurrencyToVote>(
+				&winners,
+				&assignments,
+				desired_winners,
+				total_issuance,
+				&balance_of,
+				0,
+			);
+
+			match expect_error {
+				Some(expected) => {
+					assert_eq!(verified, Err(expected));
+				},
+				None => {
+					let staked = assignment_ratio_to_staked_normalized::<_, _, _, SaturatingCurrencyToVote>(
+						assignments.clone(),
+						total_issuance,
+						&balance_of,
+					)
+					.unwrap();
+					let support = build_support_map(winners.as_ref(), staked.as_ref()).0;
+					let expected_score = evaluate_support(&support);
+
+					assert_eq!(verified, Ok(expected_score));
+				},
+			}
+		});
+	}
+}
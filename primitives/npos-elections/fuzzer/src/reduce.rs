@@ -0,0 +1,89 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2020 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Fuzzing for the `reduce` algorithm.
+//!
+//! It ensures that reducing a random solution leaves every winner's total support, as computed by
+//! `build_support_map`, unchanged, and that the edge count strictly decreases whenever `reduce`
+//! reports removed edges. The per-voter contributions within each support are not compared, and
+//! whether a reducible cycle existed is not checked independently of `reduce`'s own count.
+
+mod common;
+
+use common::{generate_random_npos_result, to_range, AccountId, Balance};
+use honggfuzz::fuzz;
+use sp_npos_elections::{
+	assignment_ratio_to_staked_normalized, build_support_map, reduce, seq_phragmen, to_without_backing,
+	SaturatingCurrencyToVote,
+};
+use sp_runtime::Perbill;
+use rand::{self, SeedableRng};
+
+fn main() {
+	loop {
+		fuzz!(|data: (usize, usize, usize, usize, u64)| {
+			let (mut target_count, mut voter_count, mut to_elect, issuance_scale_seed, seed) = data;
+			let rng = rand::rngs::SmallRng::seed_from_u64(seed);
+			target_count = to_range(target_count, 50, 2000);
+			voter_count = to_range(voter_count, 50, 1000);
+			to_elect = to_range(to_elect, 25, target_count);
+			// scale issuance from well within `u64` to far past it; most of this range pushes
+			// `total_issuance` past `u64::MAX`.
+			let issuance_scale = to_range(issuance_scale_seed, 1, 1_000_000_000_000) as Balance;
+
+			println!(
+				"++ [voter_count: {} / target_count: {} / to_elect: {} / issuance_scale: {}]",
+				voter_count, target_count, to_elect, issuance_scale,
+			);
+
+			let (candidates, voters, balance_of_tree, total_issuance) = generate_random_npos_result(
+				voter_count as u64,
+				target_count as u64,
+				issuance_scale,
+				rng,
+			);
+			let balance_of = |who: &AccountId| -> Balance { *balance_of_tree.get(who).unwrap() };
+
+			let result = seq_phragmen::<AccountId, Perbill>(to_elect, candidates, voters, None).unwrap();
+			let winners = to_without_backing(result.winners);
+
+			let mut staked = assignment_ratio_to_staked_normalized::<_, _, _, SaturatingCurrencyToVote>(
+				result.assignments,
+				total_issuance,
+				&balance_of,
+			).unwrap();
+			let before_support = build_support_map(winners.as_ref(), staked.as_ref()).0;
+			let edge_count_before: usize = staked.iter().map(|a| a.distribution.len()).sum();
+
+			let removed = reduce(&mut staked);
+
+			let after_support = build_support_map(winners.as_ref(), staked.as_ref()).0;
+			let edge_count_after: usize = staked.iter().map(|a| a.distribution.len()).sum();
+
+			assert_eq!(
+				before_support.iter().map(|(who, s)| (who.clone(), s.total)).collect::<Vec<_>>(),
+				after_support.iter().map(|(who, s)| (who.clone(), s.total)).collect::<Vec<_>>(),
+			);
+
+			if removed > 0 {
+				assert!(edge_count_after < edge_count_before);
+			}
+
+			println!("{} edges removed ({} -> {})", removed, edge_count_before, edge_count_after);
+		});
+	}
+}
@@ -0,0 +1,200 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2020 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The equalization ("balancing") step shared by [`crate::seq_phragmen`] and [`crate::phragmms`]:
+//! given a staked assignment and its support map, redistribute each voter's stake across the
+//! targets it backs so that those targets' supports are as equal as possible.
+//!
+//! [`balance`] is exposed standalone so that it can also be used to post-process a solution that
+//! did not originate from one of this crate's own solvers, such as one submitted off-chain.
+
+use crate::{ExtendedBalance, IdentifierT, StakedAssignment, SupportMap};
+use sp_arithmetic::traits::Zero;
+use sp_std::prelude::*;
+
+/// Rebalance a single voter's `assignment`, redistributing its stake across the targets it backs
+/// to even out `supports`.
+///
+/// Returns the largest support change observed across any of this voter's edges.
+fn balance_voter<AccountId: IdentifierT>(
+	assignment: &mut StakedAssignment<AccountId>,
+	supports: &mut SupportMap<AccountId>,
+) -> ExtendedBalance {
+	let mut max_change: ExtendedBalance = Zero::zero();
+
+	if assignment.distribution.len() < 2 {
+		return max_change
+	}
+
+	let stake_used = assignment.total();
+
+	// the total support backing this voter's targets, excluding this voter's own contribution.
+	let mut backing = assignment
+		.distribution
+		.iter()
+		.map(|(target, weight)| {
+			let support = supports.get(target).map(|s| s.total).unwrap_or_default();
+			(target.clone(), support.saturating_sub(*weight))
+		})
+		.collect::<Vec<_>>();
+
+	// sort ascending by the backing excluding this voter, so the first is the most under-supported
+	// target and the last is the most over-supported one.
+	backing.sort_by_key(|(_, b)| *b);
+
+	// find the largest prefix of `backing` (the lowest-backed targets) whose ideal level --
+	// spreading `stake_used` plus their own backing evenly across just that prefix -- is still
+	// at least as high as the highest backing within the prefix. Targets outside the prefix are
+	// already backed at or above that level by other voters, so this voter contributes nothing to
+	// them; topping them up too would only have inflated the ideal and shorted the targets that
+	// actually need it. Shrinking the prefix one target at a time (starting from everyone
+	// included) until this holds is the standard excluding water-fill.
+	let mut cutoff = backing.len();
+	let mut sum_backing: ExtendedBalance = backing.iter().map(|(_, b)| *b).sum();
+	let ideal_support = loop {
+		let ideal = sum_backing.saturating_add(stake_used) / (cutoff as ExtendedBalance).max(1);
+		if cutoff == 0 || ideal >= backing[cutoff - 1].1 {
+			break ideal
+		}
+		sum_backing = sum_backing.saturating_sub(backing[cutoff - 1].1);
+		cutoff -= 1;
+	};
+
+	let mut new_distribution = Vec::with_capacity(backing.len());
+	let mut allocated: ExtendedBalance = Zero::zero();
+	for (i, (target, other_backing)) in backing.iter().enumerate() {
+		let new_weight = if i >= cutoff {
+			// excluded: already at or above the ideal level without this voter's help.
+			Zero::zero()
+		} else if i + 1 == cutoff {
+			// give the last included edge whatever remains, to keep the sum exactly equal to
+			// `stake_used` despite integer-division rounding of `ideal_support`.
+			stake_used.saturating_sub(allocated)
+		} else {
+			ideal_support.saturating_sub(*other_backing)
+		};
+		allocated = allocated.saturating_add(new_weight);
+		new_distribution.push((target.clone(), new_weight));
+	}
+
+	for (target, new_weight) in new_distribution.iter() {
+		let old_weight = assignment
+			.distribution
+			.iter()
+			.find(|(t, _)| t == target)
+			.map(|(_, w)| *w)
+			.unwrap_or_default();
+
+		if let Some(support) = supports.get_mut(target) {
+			if *new_weight > old_weight {
+				support.total = support.total.saturating_add(new_weight - old_weight);
+			} else {
+				support.total = support.total.saturating_sub(old_weight - new_weight);
+			}
+		}
+
+		let change = if *new_weight > old_weight { new_weight - old_weight } else { old_weight - new_weight };
+		if change > max_change {
+			max_change = change;
+		}
+	}
+
+	assignment.distribution = new_distribution;
+	max_change
+}
+
+/// Equalize the support of every target backing the voters in `assignments`, by repeatedly
+/// sweeping over all voters and reallocating their stake towards their least-supported targets,
+/// until either the largest support change observed in a sweep is below `tolerance`, or
+/// `iterations` sweeps have run.
+///
+/// `assignments` and `supports` must be consistent with one another, i.e. `supports` must be the
+/// result of calling [`crate::build_support_map`] on (a superset of) `assignments`; this is the
+/// case for solutions produced by [`crate::seq_phragmen`] or [`crate::phragmms`], but also allows
+/// post-processing externally/off-chain submitted solutions without re-running the election.
+///
+/// Returns the number of sweeps actually performed.
+pub fn balance<AccountId: IdentifierT>(
+	assignments: &mut Vec<StakedAssignment<AccountId>>,
+	supports: &mut SupportMap<AccountId>,
+	iterations: usize,
+	tolerance: ExtendedBalance,
+) -> usize {
+	let mut iter = 0;
+	while iter < iterations {
+		let mut max_change: ExtendedBalance = Zero::zero();
+		for assignment in assignments.iter_mut() {
+			let change = balance_voter(assignment, supports);
+			if change > max_change {
+				max_change = change;
+			}
+		}
+
+		iter += 1;
+		if max_change < tolerance {
+			break
+		}
+	}
+	iter
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::Support;
+
+	fn support_map(totals: &[(u32, ExtendedBalance)]) -> SupportMap<u32> {
+		totals.iter().map(|(who, total)| (*who, Support { total: *total, voters: vec![] })).collect()
+	}
+
+	#[test]
+	fn excludes_over_backed_edges_from_the_average() {
+		// targets 10, 20 and 30 are backed, excluding this voter, by 10, 15 and 100 respectively;
+		// spreading this voter's 50 evenly across all three would pull 30's average down even
+		// though it is already backed well above what 10 and 20 need. The excluding water-fill
+		// should leave 30 alone and split the 50 between just 10 and 20.
+		let mut assignment = StakedAssignment { who: 1u32, distribution: vec![(10, 20), (20, 10), (30, 20)] };
+		let mut supports = support_map(&[(10, 30), (20, 25), (30, 120)]);
+
+		let max_change = balance_voter(&mut assignment, &mut supports);
+
+		assert!(max_change > 0);
+		assert_eq!(assignment.total(), 50);
+		let weight_of = |target: u32| {
+			assignment.distribution.iter().find(|(t, _)| *t == target).map(|(_, w)| *w).unwrap()
+		};
+		// 30 was already over-backed on its own and is excluded entirely.
+		assert_eq!(weight_of(30), 0);
+		// the other two end up close to their shared ideal of (10 + 15 + 50) / 2 = 37.
+		assert_eq!(weight_of(10), 27);
+		assert_eq!(weight_of(20), 23);
+		assert_eq!(supports[&10].total, 37);
+		assert_eq!(supports[&20].total, 38);
+		assert_eq!(supports[&30].total, 100);
+	}
+
+	#[test]
+	fn balance_is_a_no_op_on_a_single_edge() {
+		let mut assignment = StakedAssignment { who: 1u32, distribution: vec![(10, 50)] };
+		let mut supports = support_map(&[(10, 50)]);
+
+		let max_change = balance_voter(&mut assignment, &mut supports);
+
+		assert_eq!(max_change, 0);
+		assert_eq!(assignment.distribution, vec![(10, 50)]);
+	}
+}
@@ -0,0 +1,311 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2020 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A set of election algorithms to be used with a substrate runtime, typically within the
+//! election-provider pallet. Two solvers are provided: [`seq_phragmen`], which is currently in
+//! use in many production chains, and [`phragmms`], which is a newer algorithm that guarantees a
+//! constant factor approximation of the maximin support.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use sp_arithmetic::{PerThing, InnerOf, traits::{Saturating, Zero}};
+use sp_std::{prelude::*, collections::btree_map::BTreeMap, fmt::Debug};
+
+mod balancing;
+mod node;
+mod phragmen;
+mod phragmms;
+mod reduce;
+mod traits;
+mod verify;
+
+pub use balancing::balance;
+pub use phragmen::seq_phragmen;
+pub use phragmms::phragmms;
+pub use reduce::reduce;
+pub use traits::{CurrencyToVote, SaturatingCurrencyToVote};
+pub use verify::{verify_and_score, FeasibilityError};
+
+/// A type which is used in the API of this crate as a numeric weight of a vote, most often the
+/// stake of the voter in some context.
+pub type VoteWeight = u64;
+
+/// A type in which performing operations on vote weights are safe.
+pub type ExtendedBalance = u128;
+
+/// The score of an assignment, used to compare the quality of two solutions. The three values
+/// are, respectively, the minimal support in the election, the sum of all supports, and the sum
+/// of squares of all supports.
+pub type ElectionScore = [ExtendedBalance; 3];
+
+/// A type which can be used as an identifier for candidates and voters, wrapping the bare minimum
+/// of trait bounds that the algorithms in this crate rely on.
+pub trait IdentifierT: Clone + Eq + Default + Ord + Debug {}
+impl<T: Clone + Eq + Default + Ord + Debug> IdentifierT for T {}
+
+/// An error that can happen in the election algorithms of this crate.
+#[derive(Eq, PartialEq, Debug)]
+pub enum Error {
+	/// An error occurred while computing the balancing arithmetic.
+	ArithmeticError(&'static str),
+	/// The number of voters is bigger than `usize::MAX`.
+	TooManyVoters,
+}
+
+/// A pairing of a voter and their distribution of support to various candidates, as a ratio `P`
+/// of the voter's total stake.
+#[derive(Clone, Default, Debug)]
+pub struct Assignment<AccountId, P: PerThing> {
+	/// Voter's identifier.
+	pub who: AccountId,
+	/// The distribution of the voter's stake, as ratios, among the candidates it supports.
+	pub distribution: Vec<(AccountId, P)>,
+}
+
+impl<AccountId: IdentifierT, P: PerThing> Assignment<AccountId, P>
+where
+	ExtendedBalance: From<InnerOf<P>>,
+{
+	/// Convert from a ratio assignment into one with absolute values, given `stake` as the total
+	/// budget of the voter.
+	pub fn into_staked(self, stake: ExtendedBalance) -> StakedAssignment<AccountId> {
+		let distribution = self
+			.distribution
+			.into_iter()
+			.map(|(target, p)| (target, p * stake))
+			.collect::<Vec<_>>();
+
+		StakedAssignment { who: self.who, distribution }
+	}
+}
+
+/// Same as [`Assignment`], but the distribution is an absolute value, denoted in the same unit as
+/// the overall stake of the voter (e.g. in token units).
+#[derive(Clone, Default, Debug)]
+pub struct StakedAssignment<AccountId> {
+	/// Voter's identifier.
+	pub who: AccountId,
+	/// The distribution of the voter's stake, among the candidates it supports.
+	pub distribution: Vec<(AccountId, ExtendedBalance)>,
+}
+
+impl<AccountId: IdentifierT> StakedAssignment<AccountId> {
+	/// Converts self into the ratio variant, taking the sum of this assignment's `distribution` as
+	/// the voter's total stake.
+	pub fn into_assignment<P: PerThing + Zero + Saturating>(self) -> Assignment<AccountId, P>
+	where
+		ExtendedBalance: From<InnerOf<P>>,
+	{
+		let stake = self.distribution.iter().map(|(_, x)| x).sum();
+		let mut distribution = self
+			.distribution
+			.into_iter()
+			.map(|(target, weight)| (target, P::from_rational_approximation(weight, stake)))
+			.collect::<Vec<_>>();
+
+		// due to rounding errors in `PerThing`, the sum of the ratios might be slightly off from
+		// `P::ACCURACY`. Any leftover (or excess) is corrected on the last edge, the same way
+		// `assignment_ratio_to_staked_normalized` normalizes its own rounding error.
+		let one = P::from_parts(P::ACCURACY);
+		let sum = distribution.iter().fold(P::zero(), |acc, (_, p)| acc.saturating_add(*p));
+		if let Some((_, last)) = distribution.last_mut() {
+			if sum > one {
+				*last = last.saturating_sub(sum.saturating_sub(one));
+			} else if sum < one {
+				*last = last.saturating_add(one.saturating_sub(sum));
+			}
+		}
+
+		Assignment { who: self.who, distribution }
+	}
+
+	/// The total stake of this assignment, as the sum of the distribution.
+	pub fn total(&self) -> ExtendedBalance {
+		self.distribution.iter().fold(Zero::zero(), |acc, (_, x)| acc + x)
+	}
+}
+
+/// The result of running an election, regardless of the algorithm used.
+#[derive(Clone, Default, Debug)]
+pub struct ElectionResult<AccountId, P: PerThing> {
+	/// Just the winners, in no particular order.
+	pub winners: Vec<(AccountId, ExtendedBalance)>,
+	/// Individual assignments, each including the voter, and their distribution, as ratios, among
+	/// the winners.
+	pub assignments: Vec<Assignment<AccountId, P>>,
+}
+
+/// The absolute support of a single candidate, as the sum of the backing it received from all of
+/// its voters, alongside the identifiers of those voters and their individual contribution.
+#[derive(Clone, Default, Debug)]
+pub struct Support<AccountId> {
+	/// Total support.
+	pub total: ExtendedBalance,
+	/// Support from each voter, and the amount they contributed.
+	pub voters: Vec<(AccountId, ExtendedBalance)>,
+}
+
+/// A map from a candidate's identifier to its support.
+pub type SupportMap<A> = BTreeMap<A, Support<A>>;
+
+/// Build the support map from a set of winners and their staked assignments.
+///
+/// The second returned value lists every voter with an edge to a target that is not in `winners`,
+/// once per such edge; this should never happen with assignments produced by the solvers of
+/// this crate, but can happen with externally submitted data (see [`verify_and_score`]).
+pub fn build_support_map<AccountId: IdentifierT>(
+	winners: &[AccountId],
+	assignments: &[StakedAssignment<AccountId>],
+) -> (SupportMap<AccountId>, Vec<AccountId>) {
+	let mut supports = <SupportMap<AccountId>>::new();
+	winners.iter().for_each(|who| {
+		supports.insert(who.clone(), Default::default());
+	});
+
+	let mut errors = Vec::new();
+	for StakedAssignment { who, distribution } in assignments.iter() {
+		for (target, weight) in distribution.iter() {
+			if let Some(support) = supports.get_mut(target) {
+				support.total = support.total.saturating_add(*weight);
+				support.voters.push((who.clone(), *weight));
+			} else {
+				errors.push(who.clone());
+			}
+		}
+	}
+
+	(supports, errors)
+}
+
+/// Extract the minimal, sum and sum-of-squares of the support of a [`SupportMap`].
+///
+/// The resulting array is `[minimal_support, total_support, sum_of_squares]`. This is the
+/// canonical way to compare the quality of two solutions: a solution is considered better if its
+/// minimal support is higher, in case of a tie if its total support is higher, and in case of a
+/// further tie if its sum of squares is lower (i.e. support is more evenly distributed). See
+/// [`is_score_better`].
+pub fn evaluate_support<AccountId: IdentifierT>(support: &SupportMap<AccountId>) -> ElectionScore {
+	let mut minimal_support = ExtendedBalance::max_value();
+	let mut sum: ExtendedBalance = Zero::zero();
+	let mut sum_squared: ExtendedBalance = Zero::zero();
+
+	for (_, support) in support.iter() {
+		sum = sum.saturating_add(support.total);
+		let squared = support.total.saturating_mul(support.total);
+		sum_squared = sum_squared.saturating_add(squared);
+		if support.total < minimal_support {
+			minimal_support = support.total;
+		}
+	}
+
+	if support.is_empty() {
+		minimal_support = Zero::zero();
+	}
+
+	[minimal_support, sum, sum_squared]
+}
+
+/// Compare two election scores, returning `true` if `this` is strictly better than `that`, within
+/// an `epsilon` tolerance on the equality checks.
+///
+/// A score is considered better if it has a strictly higher minimal support. Ties are broken by a
+/// strictly higher total support, and then by a strictly lower sum of squares.
+pub fn is_score_better<P: PerThing>(this: ElectionScore, that: ElectionScore, epsilon: P) -> bool
+where
+	ExtendedBalance: From<InnerOf<P>>,
+{
+	this.iter()
+		.enumerate()
+		.map(|(i, e)| {
+			// `(better, worse)`, each by more than `epsilon`.
+			if i == 2 {
+				// the third element is sum of squares, lower is better.
+				let margin = epsilon.mul_ceil(that[i]);
+				(margin.saturating_add(*e) < that[i], margin.saturating_add(that[i]) < *e)
+			} else {
+				let margin = epsilon.mul_floor(that[i]);
+				(margin.saturating_add(that[i]) < *e, margin.saturating_add(*e) < that[i])
+			}
+		})
+		// the first element that differs by more than `epsilon` decides.
+		.find(|(better, worse)| *better || *worse)
+		.map_or(false, |(better, _)| better)
+}
+
+/// Strip the backing (i.e. the elected stake) off of `winners`, returning just their identifiers.
+pub fn to_without_backing<AccountId: IdentifierT>(
+	winners: Vec<(AccountId, ExtendedBalance)>,
+) -> Vec<AccountId> {
+	winners.into_iter().map(|(who, _)| who).collect::<Vec<AccountId>>()
+}
+
+/// Converts a vector of ratio assignments into their [`StakedAssignment`] variant, based on the
+/// balance of each voter, as reported by `stake_of`, converted into the [`VoteWeight`] domain via
+/// `C` and the chain's `total_issuance`.
+///
+/// The resulting vector is normalized: the sum of the distribution of each assignment is exactly
+/// equal to the (converted) stake of the assignment's voter, which is guaranteed to not overflow
+/// the `ExtendedBalance` domain.
+pub fn assignment_ratio_to_staked_normalized<AccountId, P, Balance, C>(
+	ratio: Vec<Assignment<AccountId, P>>,
+	total_issuance: Balance,
+	stake_of: impl Fn(&AccountId) -> Balance,
+) -> Result<Vec<StakedAssignment<AccountId>>, Error>
+where
+	AccountId: IdentifierT,
+	P: PerThing,
+	Balance: Clone,
+	C: CurrencyToVote<Balance>,
+	ExtendedBalance: From<InnerOf<P>>,
+{
+	ratio
+		.into_iter()
+		.map(|assignment| {
+			let stake =
+				C::to_vote(stake_of(&assignment.who), total_issuance.clone()) as ExtendedBalance;
+			let mut staked = assignment.into_staked(stake);
+
+			// due to rounding errors in `PerThing`, the sum of the staked distribution might be
+			// slightly off from `stake`. Any leftover (or excess) is corrected on the last edge,
+			// which is the standard way of normalizing rounding error in this crate.
+			let sum: ExtendedBalance = staked.distribution.iter().map(|(_, x)| x).sum();
+			if let Some((_, last)) = staked.distribution.last_mut() {
+				if sum > stake {
+					*last = last.saturating_sub(sum - stake);
+				} else if sum < stake {
+					*last = last.saturating_add(stake - sum);
+				}
+			}
+
+			Ok(staked)
+		})
+		.collect()
+}
+
+/// The inverse of [`assignment_ratio_to_staked_normalized`]: converts absolute, staked assignments
+/// back into their ratio variant.
+///
+/// Unlike its inverse, this takes no `CurrencyToVote` conversion: its input is already expressed
+/// in [`ExtendedBalance`] (the `VoteWeight` domain), so there is no `Balance` to convert out of.
+pub fn assignment_staked_to_ratio_normalized<AccountId: IdentifierT, P: PerThing + Zero + Saturating>(
+	staked: Vec<StakedAssignment<AccountId>>,
+) -> Result<Vec<Assignment<AccountId, P>>, Error>
+where
+	ExtendedBalance: From<InnerOf<P>>,
+{
+	staked.into_iter().map(|staked| Ok(staked.into_assignment::<P>())).collect()
+}
@@ -0,0 +1,109 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2020 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A small union-find-like data structure used by the [`crate::reduce`] graph algorithm to build
+//! the spanning forest of the voter/target bipartite graph, while retaining enough information
+//! about each tree edge to later walk it back when a cycle is found.
+
+use crate::{ExtendedBalance, IdentifierT};
+use sp_std::{cell::RefCell, collections::btree_map::BTreeMap, fmt, rc::Rc};
+
+/// The role that a [`Node`] plays in the bipartite voter/target graph.
+#[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Debug)]
+pub(crate) enum NodeRole {
+	/// A voter.
+	Voter,
+	/// A target (i.e. a candidate).
+	Target,
+}
+
+/// A unique identifier for a [`Node`], combining the underlying account and its role, since the
+/// same account could, in principle, appear as both a voter and a target.
+pub(crate) type NodeId<A> = (A, NodeRole);
+
+/// A node in the spanning forest built by [`crate::reduce`].
+///
+/// Nodes are organized into disjoint trees; each non-root node additionally remembers the weight
+/// of the edge that connects it to its `parent`, so that the tree path between any two connected
+/// nodes can be replayed later, edge weights and all.
+///
+/// Note: unlike a typical union-find, `root` does *not* apply path compression, since doing so
+/// would discard the very edges that [`crate::reduce`] needs to walk when it finds a cycle.
+pub(crate) struct Node<A: IdentifierT> {
+	pub(crate) id: NodeId<A>,
+	pub(crate) parent: RefCell<Option<(NodeRef<A>, ExtendedBalance)>>,
+}
+
+/// A shared, mutable reference to a [`Node`].
+pub(crate) type NodeRef<A> = Rc<Node<A>>;
+
+impl<A: IdentifierT> Node<A> {
+	/// Create a new, isolated node that is its own root.
+	pub(crate) fn new(id: NodeId<A>) -> NodeRef<A> {
+		Rc::new(Node { id, parent: RefCell::new(None) })
+	}
+
+	/// Find the root of the tree that `start` belongs to.
+	pub(crate) fn root(start: &NodeRef<A>) -> NodeRef<A> {
+		match start.parent.borrow().as_ref() {
+			Some((parent, _)) => Node::root(parent),
+			None => start.clone(),
+		}
+	}
+
+	/// Make `node` a child of `parent`, connected by an edge of `weight`.
+	pub(crate) fn set_parent_of(node: &NodeRef<A>, parent: &NodeRef<A>, weight: ExtendedBalance) {
+		*node.parent.borrow_mut() = Some((parent.clone(), weight));
+	}
+
+	/// The path from `start` up to the root of its tree, in leaf-to-root order, as a list of
+	/// `(node, weight of the edge to the previous entry)` pairs, i.e. each node paired with the
+	/// weight of the edge to its child on the path. The first entry is `start` itself, whose
+	/// weight is irrelevant and reported as `0`.
+	pub(crate) fn path_to_root(start: &NodeRef<A>) -> Vec<(NodeRef<A>, ExtendedBalance)> {
+		let mut path = vec![(start.clone(), Default::default())];
+		let mut current = start.clone();
+		while let Some((parent, weight)) = current.parent.borrow().clone() {
+			path.push((parent.clone(), weight));
+			current = parent;
+		}
+		path
+	}
+}
+
+impl<A: IdentifierT> fmt::Debug for Node<A> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "Node({:?})", self.id)
+	}
+}
+
+/// A registry that hands out a unique, shared [`Node`] per [`NodeId`], used to build the forest
+/// incrementally while iterating over edges.
+pub(crate) struct NodeRegistry<A: IdentifierT> {
+	inner: BTreeMap<NodeId<A>, NodeRef<A>>,
+}
+
+impl<A: IdentifierT> NodeRegistry<A> {
+	pub(crate) fn new() -> Self {
+		Self { inner: BTreeMap::new() }
+	}
+
+	/// Get the node for `id`, creating a fresh, isolated one if it doesn't exist yet.
+	pub(crate) fn get_or_insert(&mut self, id: NodeId<A>) -> NodeRef<A> {
+		self.inner.entry(id).or_insert_with_key(|id| Node::new(id.clone())).clone()
+	}
+}
@@ -0,0 +1,292 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2020 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Implementation of the Sequential Phragmén method, as described by the
+//! [Phragmén wiki entry](https://wiki.polkadot.network/docs/en/learn-phragmen).
+//!
+//! This is the solver that has historically backed the staking election in most Substrate based
+//! chains. See [`crate::phragmms`] for an alternative with a stronger worst-case guarantee on the
+//! minimal support.
+
+use crate::{
+	balancing::balance, Assignment, ElectionResult, ExtendedBalance, IdentifierT, VoteWeight,
+};
+use sp_arithmetic::{
+	traits::{Bounded, Saturating, Zero},
+	PerThing,
+	InnerOf,
+};
+use sp_std::prelude::*;
+
+/// Each candidate being considered, with the running state of the algorithm attached to it.
+#[derive(Clone, Debug)]
+struct Candidate<AccountId> {
+	who: AccountId,
+	elected: bool,
+	round: usize,
+	backed_stake: ExtendedBalance,
+	approval_stake: ExtendedBalance,
+}
+
+impl<AccountId> Default for Candidate<AccountId>
+where
+	AccountId: Default,
+{
+	fn default() -> Self {
+		Self {
+			who: Default::default(),
+			elected: false,
+			round: 0,
+			backed_stake: Default::default(),
+			approval_stake: Default::default(),
+		}
+	}
+}
+
+/// Each voter's edge to a candidate, with the load that has accumulated on it so far.
+#[derive(Clone, Debug)]
+struct Edge<AccountId> {
+	who: AccountId,
+	candidate_index: usize,
+	load: ExtendedBalance,
+}
+
+/// A voter, as understood by the algorithm.
+#[derive(Clone, Debug)]
+struct Voter<AccountId> {
+	who: AccountId,
+	edges: Vec<Edge<AccountId>>,
+	budget: ExtendedBalance,
+	load: ExtendedBalance,
+}
+
+/// Perform sequential Phragmén, electing `to_elect` winners out of `candidates`, weighted by the
+/// approval edges cast by `voters`.
+///
+/// If `balancing` is `Some((iterations, tolerance))`, the resulting assignment is passed through
+/// [`crate::balance`] using these parameters.
+///
+/// Returns an error if the number of voters is too large to be indexed in a `usize`, per
+/// [`crate::Error`].
+pub fn seq_phragmen<AccountId: IdentifierT, P: PerThing + Zero + Saturating>(
+	to_elect: usize,
+	candidates: Vec<AccountId>,
+	voters: Vec<(AccountId, VoteWeight, Vec<AccountId>)>,
+	balancing: Option<(usize, ExtendedBalance)>,
+) -> Result<ElectionResult<AccountId, P>, crate::Error>
+where
+	ExtendedBalance: From<InnerOf<P>>,
+{
+	let (candidates, mut voters) = setup_inputs(candidates, voters);
+
+	let mut candidates = elect_candidates(to_elect, candidates, &mut voters);
+
+	if let Some((iterations, tolerance)) = balancing {
+		let mut staked_assignments = voters
+			.iter()
+			.map(|v| crate::StakedAssignment {
+				who: v.who.clone(),
+				distribution: v
+					.edges
+					.iter()
+					.filter(|e| candidates[e.candidate_index].elected)
+					.map(|e| (e.who.clone(), e.load))
+					.collect::<Vec<_>>(),
+			})
+			.collect::<Vec<_>>();
+
+		let mut supports =
+			crate::build_support_map(&winners_of(&candidates), &staked_assignments).0;
+		balance(&mut staked_assignments, &mut supports, iterations, tolerance);
+
+		return Ok(ElectionResult {
+			winners: winners_with_backing(&candidates, &supports),
+			assignments: staked_assignments
+				.into_iter()
+				.map(|a| Ok(a.into_assignment::<P>()))
+				.collect::<Result<Vec<_>, crate::Error>>()?,
+		})
+	}
+
+	let elected_candidates = winners_of(&candidates);
+	let mut staked_assignments = voters
+		.iter()
+		.map(|v| crate::StakedAssignment {
+			who: v.who.clone(),
+			distribution: v
+				.edges
+				.iter()
+				.filter(|e| candidates[e.candidate_index].elected)
+				.map(|e| (e.who.clone(), e.load))
+				.collect::<Vec<_>>(),
+		})
+		.collect::<Vec<_>>();
+	let supports = crate::build_support_map(&elected_candidates, &staked_assignments).0;
+
+	candidates.retain(|c| c.elected);
+
+	Ok(ElectionResult {
+		winners: winners_with_backing(&candidates, &supports),
+		assignments: staked_assignments
+			.drain(..)
+			.map(|a| Ok(a.into_assignment::<P>()))
+			.collect::<Result<Vec<_>, crate::Error>>()?,
+	})
+}
+
+/// Prepare the candidates and voters for the main phragmén loop: compute each candidate's
+/// `approval_stake`, and build the voter's edges.
+fn setup_inputs<AccountId: IdentifierT>(
+	candidates: Vec<AccountId>,
+	voters: Vec<(AccountId, VoteWeight, Vec<AccountId>)>,
+) -> (Vec<Candidate<AccountId>>, Vec<Voter<AccountId>>) {
+	let mut candidates = candidates
+		.into_iter()
+		.map(|who| Candidate { who, ..Default::default() })
+		.collect::<Vec<_>>();
+
+	let mut c_idx_cache = sp_std::collections::btree_map::BTreeMap::new();
+	for (idx, c) in candidates.iter().enumerate() {
+		c_idx_cache.insert(c.who.clone(), idx);
+	}
+
+	let voters = voters
+		.into_iter()
+		.map(|(who, voter_stake, votes)| {
+			let mut edges = Vec::with_capacity(votes.len());
+			for target in votes {
+				if let Some(&idx) = c_idx_cache.get(&target) {
+					candidates[idx].approval_stake =
+						candidates[idx].approval_stake.saturating_add(voter_stake as ExtendedBalance);
+					edges.push(Edge { who: target, candidate_index: idx, load: Zero::zero() });
+				}
+			}
+			Voter { who, edges, budget: voter_stake as ExtendedBalance, load: Zero::zero() }
+		})
+		.collect::<Vec<_>>();
+
+	(candidates, voters)
+}
+
+/// Run the main sequential phragmén rounds, electing `to_elect` candidates one at a time.
+fn elect_candidates<AccountId: IdentifierT>(
+	to_elect: usize,
+	mut candidates: Vec<Candidate<AccountId>>,
+	voters: &mut Vec<Voter<AccountId>>,
+) -> Vec<Candidate<AccountId>> {
+	for round in 0..to_elect {
+		if candidates.iter().all(|c| c.elected) {
+			break
+		}
+
+		for c in candidates.iter_mut().filter(|c| !c.elected) {
+			c.backed_stake = if c.approval_stake.is_zero() {
+				ExtendedBalance::max_value()
+			} else {
+				Zero::zero()
+			};
+		}
+
+		// `backed_stake(c)` is the total stake that would actually back `c` right now: the
+		// *remaining* free capacity (`budget - load`) of each approving voter, not its full,
+		// never-reduced budget. Voters who already committed some or all of their budget to a
+		// previously elected candidate have correspondingly less left to offer `c`, so this
+		// score shrinks across rounds as edges get elected, rather than being a constant
+		// function of `approval_stake` alone.
+		for v in voters.iter() {
+			let free_budget = v.budget.saturating_sub(v.load);
+			for e in v.edges.iter() {
+				let c = &mut candidates[e.candidate_index];
+				if !c.elected && !c.approval_stake.is_zero() {
+					c.backed_stake = c.backed_stake.saturating_add(free_budget);
+				}
+			}
+		}
+
+		if let Some(winner_idx) = candidates
+			.iter()
+			.enumerate()
+			.filter(|(_, c)| !c.elected && !c.approval_stake.is_zero())
+			.min_by_key(|(_, c)| ExtendedBalance::max_value() - c.backed_stake)
+			.map(|(idx, _)| idx)
+		{
+			let winner = &mut candidates[winner_idx];
+			winner.elected = true;
+			winner.round = round;
+
+			for v in voters.iter_mut() {
+				if let Some(edge) = v.edges.iter_mut().find(|e| e.candidate_index == winner_idx) {
+					edge.load = v.budget.saturating_sub(v.load);
+					v.load = v.budget;
+				}
+			}
+		} else {
+			break
+		}
+	}
+
+	candidates
+}
+
+fn winners_of<AccountId: IdentifierT>(candidates: &[Candidate<AccountId>]) -> Vec<AccountId> {
+	candidates.iter().filter(|c| c.elected).map(|c| c.who.clone()).collect()
+}
+
+fn winners_with_backing<AccountId: IdentifierT>(
+	candidates: &[Candidate<AccountId>],
+	supports: &crate::SupportMap<AccountId>,
+) -> Vec<(AccountId, ExtendedBalance)> {
+	candidates
+		.iter()
+		.filter(|c| c.elected)
+		.map(|c| (c.who.clone(), supports.get(&c.who).map(|s| s.total).unwrap_or_default()))
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use sp_arithmetic::Perbill;
+
+	#[test]
+	fn scores_unelected_candidates_by_remaining_free_budget() {
+		// 1 and 2 both approve candidate 10, which wins round one on their combined backing; 1
+		// also approves 30, which must *not* still look attractive in round two using 1's full,
+		// untouched budget -- 1 has nothing left to give after committing everything to 10.
+		let candidates = vec![10, 20, 30];
+		let voters = vec![(1, 300, vec![10, 30]), (2, 50, vec![10]), (4, 20, vec![20])];
+
+		let result = seq_phragmen::<u32, Perbill>(2, candidates, voters, None).unwrap();
+
+		let winners =
+			result.winners.into_iter().collect::<sp_std::collections::btree_map::BTreeMap<_, _>>();
+		assert_eq!(winners.keys().copied().collect::<Vec<_>>(), vec![10, 20]);
+		assert_eq!(winners[&10], 350);
+		assert_eq!(winners[&20], 20);
+	}
+
+	#[test]
+	fn elects_fewer_than_to_elect_when_candidates_run_out() {
+		let candidates = vec![10, 20];
+		let voters = vec![(1, 100, vec![10])];
+
+		let result = seq_phragmen::<u32, Perbill>(5, candidates, voters, None).unwrap();
+
+		assert_eq!(result.winners.len(), 1);
+		assert_eq!(result.winners[0].0, 10);
+	}
+}
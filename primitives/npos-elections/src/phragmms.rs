@@ -0,0 +1,339 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2020 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! An implementation of the maximin-support (MMS) election algorithm, often referred to as
+//! "Phragmms", a portmanteau of Phragmén and MMS.
+//!
+//! Unlike [`crate::seq_phragmen`], which only guarantees that each round improves the *existing*
+//! score, Phragmms guarantees that the minimal support of its final solution (the first element
+//! of the score reported by [`crate::evaluate_support`]) is within a constant factor of the
+//! optimal achievable minimal support. This comes at a similar computational cost to sequential
+//! Phragmén.
+
+use crate::{
+	balancing::balance, Assignment, ElectionResult, ExtendedBalance, IdentifierT, StakedAssignment,
+	VoteWeight,
+};
+use sp_arithmetic::{traits::{Saturating, Zero}, InnerOf, PerThing};
+use sp_std::prelude::*;
+
+/// A candidate, as tracked by the Phragmms algorithm.
+#[derive(Clone, Debug)]
+struct Candidate<AccountId> {
+	who: AccountId,
+	elected: bool,
+	approval_stake: ExtendedBalance,
+	backed_stake: ExtendedBalance,
+}
+
+/// A voter's edge to one of the candidates it approves of.
+#[derive(Clone, Debug)]
+struct Edge {
+	candidate_index: usize,
+}
+
+/// A voter, as tracked by the Phragmms algorithm.
+#[derive(Clone, Debug)]
+struct Voter<AccountId> {
+	who: AccountId,
+	budget: ExtendedBalance,
+	edges: Vec<Edge>,
+	_marker: sp_std::marker::PhantomData<AccountId>,
+}
+
+/// Fixed-point precision used to express "the fraction of a candidate's support a voter is
+/// already responsible for" as an integer ratio.
+const OWED_PRECISION: ExtendedBalance = 1_000_000_000;
+
+/// For every one of `voter`'s edges to an already-elected candidate, the share of that
+/// candidate's `backed_stake` contributed by this voter (`edge_weight / backed_stake`) is a
+/// share of `voter.budget` it is already committed to, and hence no longer free to offer a new
+/// candidate. This computes that owed amount for each such edge (`0` for edges to unelected
+/// candidates, or to a candidate that -- in principle should not happen -- won with no backing at
+/// all, guarding the division by zero), capping their total at `voter.budget` should rounding
+/// ever push the raw shares past `100%`, and returns `(owed_per_edge, free_budget)`.
+fn voter_allocation<AccountId>(
+	voter: &Voter<AccountId>,
+	candidates: &[Candidate<AccountId>],
+	edge_weights: &[ExtendedBalance],
+) -> (Vec<ExtendedBalance>, ExtendedBalance) {
+	let mut shares = vec![Zero::zero(); voter.edges.len()];
+	let mut total_share: ExtendedBalance = Zero::zero();
+
+	for (e_idx, e) in voter.edges.iter().enumerate() {
+		let backing = &candidates[e.candidate_index];
+		if !backing.elected || backing.backed_stake.is_zero() {
+			continue
+		}
+		let share = edge_weights[e_idx].saturating_mul(OWED_PRECISION) / backing.backed_stake;
+		shares[e_idx] = share;
+		total_share = total_share.saturating_add(share);
+	}
+
+	if total_share > OWED_PRECISION {
+		for share in shares.iter_mut() {
+			*share = share.saturating_mul(OWED_PRECISION) / total_share;
+		}
+		total_share = OWED_PRECISION;
+	}
+
+	let owed = shares
+		.iter()
+		.map(|share| voter.budget.saturating_mul(*share) / OWED_PRECISION)
+		.collect::<Vec<_>>();
+	// derived from `owed` itself, rather than recomputed from `total_share`, so that the two
+	// always sum back to exactly `voter.budget` despite any per-edge rounding.
+	let free_budget = voter
+		.budget
+		.saturating_sub(owed.iter().fold(Zero::zero(), |acc: ExtendedBalance, o| acc.saturating_add(*o)));
+
+	(owed, free_budget)
+}
+
+/// Elect `to_elect` winners out of `candidates`, approved by `voters`, using the maximin-support
+/// (MMS) algorithm.
+///
+/// `balancing` behaves exactly as it does in [`crate::seq_phragmen`]: when `Some((iterations,
+/// tolerance))`, the final assignment is passed through [`crate::balance`].
+pub fn phragmms<AccountId: IdentifierT, P: PerThing + Zero + Saturating>(
+	to_elect: usize,
+	candidates: Vec<AccountId>,
+	voters: Vec<(AccountId, VoteWeight, Vec<AccountId>)>,
+	balancing: Option<(usize, ExtendedBalance)>,
+) -> Result<ElectionResult<AccountId, P>, crate::Error>
+where
+	ExtendedBalance: From<InnerOf<P>>,
+{
+	let mut c_idx_cache = sp_std::collections::btree_map::BTreeMap::new();
+	let mut candidates = candidates
+		.into_iter()
+		.enumerate()
+		.map(|(idx, who)| {
+			c_idx_cache.insert(who.clone(), idx);
+			Candidate { who, elected: false, approval_stake: Zero::zero(), backed_stake: Zero::zero() }
+		})
+		.collect::<Vec<_>>();
+
+	let mut voters = voters
+		.into_iter()
+		.map(|(who, stake, votes)| {
+			let stake = stake as ExtendedBalance;
+			let edges = votes
+				.into_iter()
+				.filter_map(|v| c_idx_cache.get(&v).copied())
+				.map(|candidate_index| Edge { candidate_index })
+				.collect::<Vec<_>>();
+			for e in edges.iter() {
+				candidates[e.candidate_index].approval_stake =
+					candidates[e.candidate_index].approval_stake.saturating_add(stake);
+			}
+			Voter { who, budget: stake, edges, _marker: Default::default() }
+		})
+		.collect::<Vec<_>>();
+
+	// per-voter, per-candidate edge weight (the amount of `budget` a voter has put behind one of
+	// its elected candidates so far).
+	let mut edge_weights: Vec<Vec<ExtendedBalance>> =
+		voters.iter().map(|v| vec![Zero::zero(); v.edges.len()]).collect();
+
+	for _round in 0..to_elect {
+		if candidates.iter().filter(|c| !c.elected).next().is_none() {
+			break
+		}
+
+		// for every not-yet-elected candidate, compute the maximum achievable minimal support `t`
+		// if it were elected next, as the sum of the free budget (see [`voter_allocation`]) of
+		// every voter approving it.
+		let mut best: Option<(usize, ExtendedBalance)> = None;
+		for (c_idx, c) in candidates.iter().enumerate() {
+			if c.elected || c.approval_stake.is_zero() {
+				continue
+			}
+
+			let mut max_score: ExtendedBalance = Zero::zero();
+			for (v_idx, v) in voters.iter().enumerate() {
+				if !v.edges.iter().any(|e| e.candidate_index == c_idx) {
+					continue
+				}
+
+				let (_, free_budget) = voter_allocation(v, &candidates, &edge_weights[v_idx]);
+				max_score = max_score.saturating_add(free_budget);
+			}
+
+			if best.map(|(_, s)| max_score > s).unwrap_or(true) {
+				best = Some((c_idx, max_score));
+			}
+		}
+
+		let winner_idx = match best {
+			Some((idx, _)) => idx,
+			None => break,
+		};
+
+		candidates[winner_idx].elected = true;
+
+		// insert the new edges: every approving voter puts its free budget behind the newly
+		// elected candidate, reallocating away from the candidates it already backs exactly as
+		// much as `voter_allocation` says it now owes them, so its total committed stake never
+		// exceeds its `budget`. Collected into `edge_updates`/`backed_stake_deltas` and applied
+		// only after every voter has been read, so that one voter's reallocation in this round
+		// does not affect another's within the same round.
+		let mut newly_backed: ExtendedBalance = Zero::zero();
+		let mut edge_updates: Vec<(usize, usize, ExtendedBalance)> = Vec::new();
+		let mut backed_stake_deltas: Vec<(usize, ExtendedBalance, bool)> = Vec::new();
+
+		for (v_idx, v) in voters.iter().enumerate() {
+			let winner_edge_idx = match v.edges.iter().position(|e| e.candidate_index == winner_idx)
+			{
+				Some(i) => i,
+				None => continue,
+			};
+
+			let (owed, free_budget) = voter_allocation(v, &candidates, &edge_weights[v_idx]);
+
+			for (e_idx, e) in v.edges.iter().enumerate() {
+				if e_idx == winner_edge_idx {
+					continue
+				}
+				let old_weight = edge_weights[v_idx][e_idx];
+				let new_weight = owed[e_idx];
+				if new_weight == old_weight {
+					continue
+				}
+				edge_updates.push((v_idx, e_idx, new_weight));
+				backed_stake_deltas.push(if new_weight > old_weight {
+					(e.candidate_index, new_weight - old_weight, true)
+				} else {
+					(e.candidate_index, old_weight - new_weight, false)
+				});
+			}
+
+			edge_updates.push((v_idx, winner_edge_idx, free_budget));
+			newly_backed = newly_backed.saturating_add(free_budget);
+		}
+
+		for (v_idx, e_idx, new_weight) in edge_updates {
+			edge_weights[v_idx][e_idx] = new_weight;
+		}
+		for (c_idx, delta, is_increase) in backed_stake_deltas {
+			candidates[c_idx].backed_stake = if is_increase {
+				candidates[c_idx].backed_stake.saturating_add(delta)
+			} else {
+				candidates[c_idx].backed_stake.saturating_sub(delta)
+			};
+		}
+		candidates[winner_idx].backed_stake = newly_backed;
+	}
+
+	let elected_candidates =
+		candidates.iter().filter(|c| c.elected).map(|c| c.who.clone()).collect::<Vec<_>>();
+
+	let mut staked_assignments = voters
+		.iter()
+		.enumerate()
+		.map(|(v_idx, v)| StakedAssignment {
+			who: v.who.clone(),
+			distribution: v
+				.edges
+				.iter()
+				.enumerate()
+				.filter(|(_, e)| candidates[e.candidate_index].elected)
+				.map(|(e_idx, e)| (candidates[e.candidate_index].who.clone(), edge_weights[v_idx][e_idx]))
+				.collect::<Vec<_>>(),
+		})
+		.collect::<Vec<_>>();
+
+	let mut supports = crate::build_support_map(&elected_candidates, &staked_assignments).0;
+
+	if let Some((iterations, tolerance)) = balancing {
+		balance(&mut staked_assignments, &mut supports, iterations, tolerance);
+	}
+
+	let winners = candidates
+		.into_iter()
+		.filter(|c| c.elected)
+		.map(|c| {
+			let backing = supports.get(&c.who).map(|s| s.total).unwrap_or_default();
+			(c.who, backing)
+		})
+		.collect::<Vec<_>>();
+
+	let assignments = staked_assignments
+		.into_iter()
+		.map(|a| Ok(a.into_assignment::<P>()))
+		.collect::<Result<Vec<Assignment<AccountId, P>>, crate::Error>>()?;
+
+	Ok(ElectionResult { winners, assignments })
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use sp_arithmetic::Perbill;
+
+	#[test]
+	fn scores_unelected_candidates_by_owed_adjusted_free_budget() {
+		// voter 1 approves both 10 (which wins round one on the combined backing of 1 and 2) and
+		// 30. Once 10 is elected, 1 owes it the share of `backed_stake` its edge represents
+		// (300/350ths of its budget), leaving only the remainder (43) free for 30 -- not 1's
+		// full, untouched budget, and not zero either (1's edge to 10 is correspondingly shrunk
+		// to free it up).
+		let candidates = vec![10, 20, 30];
+		let voters = vec![(1, 300, vec![10, 30]), (2, 50, vec![10]), (4, 20, vec![20])];
+
+		let result = phragmms::<u32, Perbill>(2, candidates, voters, None).unwrap();
+
+		let winners =
+			result.winners.into_iter().collect::<sp_std::collections::btree_map::BTreeMap<_, _>>();
+		assert_eq!(winners.keys().copied().collect::<Vec<_>>(), vec![10, 30]);
+		assert_eq!(winners[&10], 307);
+		assert_eq!(winners[&30], 43);
+	}
+
+	#[test]
+	fn achieves_a_higher_minimal_support_than_seq_phragmen_on_the_same_input() {
+		// on this input, `seq_phragmen` greedily commits 1's entire budget to 10 and never
+		// revisits that choice, so it elects 10 and 20, leaving 20 backed only by 4's budget of
+		// 20. `phragmms` reconsiders how much 1 owes 10 once a second candidate is on the table,
+		// freeing up enough of 1's budget to make 30 (backed only by 1) the more attractive pick,
+		// for a minimal support of 43 -- strictly better than seq_phragmen's 20.
+		let candidates = vec![10, 20, 30];
+		let voters = vec![(1, 300, vec![10, 30]), (2, 50, vec![10]), (4, 20, vec![20])];
+
+		let seq_phragmen_result =
+			crate::seq_phragmen::<u32, Perbill>(2, candidates.clone(), voters.clone(), None).unwrap();
+		let phragmms_result = phragmms::<u32, Perbill>(2, candidates, voters, None).unwrap();
+
+		let minimal_support = |winners: Vec<(u32, ExtendedBalance)>| {
+			winners.into_iter().map(|(_, backing)| backing).min().unwrap()
+		};
+
+		assert_eq!(minimal_support(seq_phragmen_result.winners), 20);
+		assert_eq!(minimal_support(phragmms_result.winners), 43);
+	}
+
+	#[test]
+	fn stops_when_no_unelected_candidate_has_any_approval() {
+		let candidates = vec![10, 20];
+		let voters = vec![(1, 100, vec![10])];
+
+		let result = phragmms::<u32, Perbill>(5, candidates, voters, None).unwrap();
+
+		assert_eq!(result.winners.len(), 1);
+		assert_eq!(result.winners[0].0, 10);
+	}
+}
@@ -0,0 +1,352 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2020 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Reduce the number of edges (voter-to-target assignments) in a solution without changing either
+//! the total stake of any voter or the support of any target, i.e. without changing
+//! [`crate::build_support_map`]'s output. This matters on-chain, where the size of a solution is
+//! paid for directly.
+//!
+//! The algorithm runs in two phases:
+//!
+//! 1. A specialized pass that cancels length-4 cycles: whenever two voters both back the same two
+//!    targets, weight can be pushed around that 4-cycle until one of the four edges hits zero.
+//! 2. A general pass that builds a spanning forest of the bipartite voter/target graph. Any edge
+//!    that would close a cycle in that forest instead has its weight pushed, alternately adding
+//!    and subtracting, around the cycle it closes, by the smallest weight among the edges being
+//!    decreased; that edge is then removed. The forest is not updated after a cancellation, so a
+//!    later cycle that runs through an edge already removed this way is skipped.
+
+use crate::{
+	node::{Node, NodeRef, NodeRegistry, NodeRole},
+	ExtendedBalance, IdentifierT, StakedAssignment,
+};
+use sp_arithmetic::traits::Zero;
+use sp_std::{collections::btree_map::BTreeMap, prelude::*, rc::Rc};
+
+/// Reduce the number of edges in `assignments`, without altering any voter's total stake or any
+/// target's total support. Every cycle that is cancelled loses at least one edge; cycles skipped
+/// by the second phase (see the module docs) remain, so the result is not guaranteed to be
+/// free of cycles.
+///
+/// Returns the number of edges that were removed.
+pub fn reduce<A: IdentifierT>(assignments: &mut Vec<StakedAssignment<A>>) -> u32 {
+	let mut removed = reduce_4_cycles(assignments);
+	removed = removed.saturating_add(reduce_all(assignments));
+	removed
+}
+
+fn edge_weight<A: IdentifierT>(assignment: &StakedAssignment<A>, target: &A) -> Option<ExtendedBalance> {
+	assignment.distribution.iter().find(|(t, _)| t == target).map(|(_, w)| *w)
+}
+
+fn set_edge_weight<A: IdentifierT>(
+	assignments: &mut Vec<StakedAssignment<A>>,
+	voter: &A,
+	target: &A,
+	weight: ExtendedBalance,
+) {
+	if let Some(assignment) = assignments.iter_mut().find(|a| &a.who == voter) {
+		if let Some(entry) = assignment.distribution.iter_mut().find(|(t, _)| t == target) {
+			entry.1 = weight;
+		}
+	}
+}
+
+fn drop_zero_edges<A: IdentifierT>(assignments: &mut Vec<StakedAssignment<A>>) -> u32 {
+	let mut removed = 0u32;
+	for assignment in assignments.iter_mut() {
+		let before = assignment.distribution.len();
+		assignment.distribution.retain(|(_, w)| !w.is_zero());
+		removed += (before - assignment.distribution.len()) as u32;
+	}
+	removed
+}
+
+/// Phase one: repeatedly find two voters that both back the same pair of targets, and cancel
+/// weight around that 4-cycle until an edge disappears.
+fn reduce_4_cycles<A: IdentifierT>(assignments: &mut Vec<StakedAssignment<A>>) -> u32 {
+	let mut removed = 0u32;
+	loop {
+		let mut pairs: BTreeMap<(A, A), Vec<usize>> = BTreeMap::new();
+		for (v_idx, assignment) in assignments.iter().enumerate() {
+			let targets = assignment.distribution.iter().map(|(t, _)| t).collect::<Vec<_>>();
+			for i in 0..targets.len() {
+				for j in (i + 1)..targets.len() {
+					let pair = if targets[i] <= targets[j] {
+						(targets[i].clone(), targets[j].clone())
+					} else {
+						(targets[j].clone(), targets[i].clone())
+					};
+					pairs.entry(pair).or_insert_with(Vec::new).push(v_idx);
+				}
+			}
+		}
+
+		let mut progressed = false;
+		'outer: for ((t1, t2), voters) in pairs.iter() {
+			for i in 0..voters.len() {
+				for j in (i + 1)..voters.len() {
+					let cancelled = cancel_4_cycle(assignments, voters[i], voters[j], t1, t2);
+					if cancelled > 0 {
+						removed += cancelled;
+						progressed = true;
+						break 'outer
+					}
+				}
+			}
+		}
+
+		if !progressed {
+			break
+		}
+	}
+	removed
+}
+
+/// Try to cancel the 4-cycle `v1 - t1 - v2 - t2 - v1`. Returns the number of edges that dropped to
+/// zero and were removed as a result (`0` if no weight was moved).
+fn cancel_4_cycle<A: IdentifierT>(
+	assignments: &mut Vec<StakedAssignment<A>>,
+	v1: usize,
+	v2: usize,
+	t1: &A,
+	t2: &A,
+) -> u32 {
+	let (w1_t1, w1_t2, w2_t1, w2_t2) = match (
+		edge_weight(&assignments[v1], t1),
+		edge_weight(&assignments[v1], t2),
+		edge_weight(&assignments[v2], t1),
+		edge_weight(&assignments[v2], t2),
+	) {
+		(Some(a), Some(b), Some(c), Some(d)) => (a, b, c, d),
+		_ => return 0,
+	};
+
+	// pushing `eps` around the cycle v1->t1 (+), v2->t1 (-), v2->t2 (+), v1->t2 (-) leaves every
+	// voter's total and every target's total support unchanged.
+	let eps = w2_t1.min(w1_t2);
+	if eps.is_zero() {
+		return 0
+	}
+
+	let voter1 = assignments[v1].who.clone();
+	let voter2 = assignments[v2].who.clone();
+	set_edge_weight(assignments, &voter1, t1, w1_t1.saturating_add(eps));
+	set_edge_weight(assignments, &voter1, t2, w1_t2.saturating_sub(eps));
+	set_edge_weight(assignments, &voter2, t1, w2_t1.saturating_sub(eps));
+	set_edge_weight(assignments, &voter2, t2, w2_t2.saturating_add(eps));
+
+	drop_zero_edges(assignments)
+}
+
+/// Flip every parent pointer on the path from `node` to the root of its tree, so that `node`
+/// itself becomes the new root. This lets a fresh edge be attached directly to `node` regardless
+/// of where it used to sit in the tree.
+fn reroot<A: IdentifierT>(node: &NodeRef<A>) {
+	let path = Node::path_to_root(node);
+	for i in (1..path.len()).rev() {
+		Node::set_parent_of(&path[i].0, &path[i - 1].0, path[i].1);
+	}
+	*node.parent.borrow_mut() = None;
+}
+
+/// Turn a tree edge between `a` and `b` (in either order) into a `(voter, target, weight)` triple,
+/// using each node's recorded [`NodeRole`] to tell which side is which.
+fn as_voter_target_edge<A: IdentifierT>(
+	a: &NodeRef<A>,
+	b: &NodeRef<A>,
+	weight: ExtendedBalance,
+) -> (A, A, ExtendedBalance) {
+	if a.id.1 == NodeRole::Voter {
+		(a.id.0.clone(), b.id.0.clone(), weight)
+	} else {
+		(b.id.0.clone(), a.id.0.clone(), weight)
+	}
+}
+
+/// Phase two: build a spanning forest of the bipartite voter/target graph edge by edge; whenever
+/// an edge would close a cycle, cancel weight alternately around that cycle instead of adding it
+/// to the forest.
+fn reduce_all<A: IdentifierT>(assignments: &mut Vec<StakedAssignment<A>>) -> u32 {
+	let mut removed = 0u32;
+	let mut registry = NodeRegistry::new();
+
+	// voter and target indices are stable for the duration of this pass: we only ever zero out
+	// edge weights, never re-index `assignments` itself, until the very end of each cycle-closing
+	// step.
+	let edges = assignments
+		.iter()
+		.flat_map(|a| a.distribution.iter().map(move |(t, _)| (a.who.clone(), t.clone())))
+		.collect::<Vec<_>>();
+
+	for (voter_id, target) in edges {
+		let weight = match assignments
+			.iter()
+			.find(|a| a.who == voter_id)
+			.and_then(|a| edge_weight(a, &target))
+		{
+			Some(w) if !w.is_zero() => w,
+			_ => continue,
+		};
+
+		let voter_node = registry.get_or_insert((voter_id.clone(), NodeRole::Voter));
+		let target_node = registry.get_or_insert((target.clone(), NodeRole::Target));
+
+		let root_v = Node::root(&voter_node);
+		let root_t = Node::root(&target_node);
+
+		if !Rc::ptr_eq(&root_v, &root_t) {
+			// no cycle yet: merge the two trees by re-rooting the voter's tree at the voter
+			// itself, then attaching it to the target directly via this edge.
+			reroot(&voter_node);
+			Node::set_parent_of(&voter_node, &target_node, weight);
+			continue
+		}
+
+		// this edge closes a cycle: find the path from the voter and the target up to their
+		// lowest common ancestor, then alternately add/subtract the smallest weight among the
+		// decreased edges around the loop.
+		let path_v = Node::path_to_root(&voter_node);
+		let path_t = Node::path_to_root(&target_node);
+
+		let v_index_of: BTreeMap<_, _> =
+			path_v.iter().enumerate().map(|(i, (n, _))| (n.id.clone(), i)).collect();
+
+		let (lca_v, lca_t) = match path_t
+			.iter()
+			.enumerate()
+			.find_map(|(i, (n, _))| v_index_of.get(&n.id).map(|&vi| (vi, i)))
+		{
+			Some(found) => found,
+			// should not happen given `root_v == root_t`, but guard defensively.
+			None => continue,
+		};
+
+		// cycle edges, in order, starting with the new edge itself (always treated as a `+`).
+		let mut cycle: Vec<(A, A, ExtendedBalance)> = vec![(voter_id.clone(), target.clone(), weight)];
+		for i in 0..lca_t {
+			cycle.push(as_voter_target_edge(&path_t[i].0, &path_t[i + 1].0, path_t[i + 1].1));
+		}
+		for i in (0..lca_v).rev() {
+			cycle.push(as_voter_target_edge(&path_v[i].0, &path_v[i + 1].0, path_v[i + 1].1));
+		}
+
+		// weights recorded in the forest go stale once an earlier cancellation changes or removes
+		// a tree edge: use the current weights, and skip the cycle if one of its edges is gone.
+		let cycle = match cycle
+			.into_iter()
+			.map(|(voter, target, _)| {
+				let a = assignments.iter().find(|a| a.who == voter)?;
+				edge_weight(a, &target).map(|w| (voter, target, w))
+			})
+			.collect::<Option<Vec<_>>>()
+		{
+			Some(cycle) => cycle,
+			None => continue,
+		};
+
+		let decreasing_min = cycle
+			.iter()
+			.enumerate()
+			.filter(|(i, _)| i % 2 == 1)
+			.map(|(_, (_, _, w))| *w)
+			.min()
+			.unwrap_or_default();
+
+		if decreasing_min.is_zero() {
+			continue
+		}
+
+		for (i, (voter, target, w)) in cycle.into_iter().enumerate() {
+			let new_weight = if i % 2 == 0 {
+				w.saturating_add(decreasing_min)
+			} else {
+				w.saturating_sub(decreasing_min)
+			};
+			set_edge_weight(assignments, &voter, &target, new_weight);
+		}
+
+		removed += drop_zero_edges(assignments);
+	}
+
+	removed
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn cancels_a_4_cycle() {
+		// voters 1 and 2 both back targets 10 and 20; pushing weight around the cycle they form
+		// drops one edge from each voter (2 in total) without changing any voter's total or any
+		// target's support.
+		let mut assignments = vec![
+			StakedAssignment { who: 1u32, distribution: vec![(10, 10), (20, 5)] },
+			StakedAssignment { who: 2u32, distribution: vec![(10, 5), (20, 10)] },
+		];
+
+		let removed = reduce(&mut assignments);
+
+		assert_eq!(removed, 2);
+		assert_eq!(assignments[0].distribution, vec![(10, 15)]);
+		assert_eq!(assignments[1].distribution, vec![(20, 15)]);
+		// every voter's total stake, and every target's total support, is preserved.
+		assert_eq!(assignments[0].total(), 15);
+		assert_eq!(assignments[1].total(), 15);
+	}
+
+	#[test]
+	fn is_a_no_op_without_redundant_edges() {
+		let mut assignments =
+			vec![StakedAssignment { who: 1u32, distribution: vec![(10, 10), (20, 10)] }];
+
+		let removed = reduce(&mut assignments);
+
+		assert_eq!(removed, 0);
+		assert_eq!(assignments[0].distribution, vec![(10, 10), (20, 10)]);
+	}
+
+	#[test]
+	fn reduce_all_cancels_a_cycle_the_4_cycle_pass_cannot() {
+		// 1, 2 and 3 form a 6-edge cycle (1-10-3-30-2-20-1), but no two voters back the same pair
+		// of targets, so `reduce_4_cycles` has nothing to do with it; only the general
+		// spanning-forest pass can close this loop.
+		let assignments = vec![
+			StakedAssignment { who: 1u32, distribution: vec![(10, 10), (20, 5)] },
+			StakedAssignment { who: 2u32, distribution: vec![(20, 8), (30, 3)] },
+			StakedAssignment { who: 3u32, distribution: vec![(30, 6), (10, 4)] },
+		];
+		let edges_before: usize = assignments.iter().map(|a| a.distribution.len()).sum();
+		let (supports_before, _) = crate::build_support_map(&[10, 20, 30], &assignments);
+
+		let mut reduced = assignments.clone();
+		let removed = reduce(&mut reduced);
+
+		let edges_after: usize = reduced.iter().map(|a| a.distribution.len()).sum();
+		let (supports_after, _) = crate::build_support_map(&[10, 20, 30], &reduced);
+
+		assert!(removed > 0);
+		assert!(edges_after < edges_before);
+		for who in [10u32, 20, 30] {
+			assert_eq!(supports_before[&who].total, supports_after[&who].total);
+		}
+		for (before, after) in assignments.iter().zip(reduced.iter()) {
+			assert_eq!(before.total(), after.total());
+		}
+	}
+}
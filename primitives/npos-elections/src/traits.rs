@@ -0,0 +1,113 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2020 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A decoupling point between a chain's own balance type and the [`crate::VoteWeight`] domain
+//! that the election algorithms in this crate actually operate in.
+//!
+//! Plain `u64` vote weights overflow, or lose meaningful precision, once a chain's total issuance
+//! grows past `u64::MAX`. [`CurrencyToVote`] lets a caller plug in whatever conversion makes sense
+//! for their balance type, while [`SaturatingCurrencyToVote`] provides a sensible, overflow-safe
+//! default for 128-bit balances.
+
+use crate::VoteWeight;
+
+/// Converts a balance value into the [`VoteWeight`] domain used internally by this crate's
+/// solvers, and back.
+///
+/// `total_issuance` is provided to both directions so that implementations can scale relative to
+/// the total supply, rather than needing a fixed, chain-specific constant.
+pub trait CurrencyToVote<Balance> {
+	/// Convert `value` into a [`VoteWeight`], given the chain's `total_issuance`.
+	fn to_vote(value: Balance, total_issuance: Balance) -> VoteWeight;
+
+	/// The inverse of [`Self::to_vote`]: convert `value` back into `Balance`, given the same
+	/// `total_issuance` that was used to produce it.
+	fn to_currency(value: VoteWeight, total_issuance: Balance) -> Balance;
+}
+
+/// A saturating [`CurrencyToVote`] implementation for `u128` balances.
+///
+/// While `total_issuance` fits in a `u64`, the conversion is the identity. Once it exceeds
+/// `u64::MAX`, balances are scaled down by `total_issuance / u64::MAX` (rounded down, floored at
+/// `1`) before being narrowed to a `u64`, and scaled back up by the same factor on the way out.
+pub struct SaturatingCurrencyToVote;
+
+impl SaturatingCurrencyToVote {
+	fn factor(total_issuance: u128) -> u128 {
+		(total_issuance / u128::from(u64::MAX)).max(1)
+	}
+}
+
+impl CurrencyToVote<u128> for SaturatingCurrencyToVote {
+	fn to_vote(value: u128, total_issuance: u128) -> VoteWeight {
+		if total_issuance > u128::from(u64::MAX) {
+			// `factor` is only a rough scale-down (it floors at `1` for any `total_issuance` up
+			// to `2 * u64::MAX`), so a large enough individual `value` can still overshoot
+			// `u64::MAX` after the division; clamp explicitly rather than let the cast wrap.
+			(value / Self::factor(total_issuance)).min(u128::from(u64::MAX)) as VoteWeight
+		} else {
+			value as VoteWeight
+		}
+	}
+
+	fn to_currency(value: VoteWeight, total_issuance: u128) -> u128 {
+		if total_issuance > u128::from(u64::MAX) {
+			u128::from(value).saturating_mul(Self::factor(total_issuance))
+		} else {
+			u128::from(value)
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn identity_below_u64_max() {
+		assert_eq!(SaturatingCurrencyToVote::to_vote(1_000, 1_000_000), 1_000);
+		assert_eq!(SaturatingCurrencyToVote::to_vote(u64::MAX as u128, u64::MAX as u128), u64::MAX);
+	}
+
+	#[test]
+	fn saturates_instead_of_wrapping_just_past_u64_max() {
+		// `total_issuance` just over `u64::MAX` puts `factor` in its worst case: it still floors
+		// to `1`, so a whale whose balance is the entire issuance must not be scaled down to
+		// something tiny by a wrapping cast.
+		let total_issuance = u64::MAX as u128 + 1_000;
+		assert_eq!(SaturatingCurrencyToVote::factor(total_issuance), 1);
+		assert_eq!(SaturatingCurrencyToVote::to_vote(total_issuance, total_issuance), u64::MAX);
+	}
+
+	#[test]
+	fn scales_down_well_past_u64_max() {
+		let total_issuance = (u64::MAX as u128) * 10;
+		let vote = SaturatingCurrencyToVote::to_vote(total_issuance, total_issuance);
+		assert_eq!(vote, u64::MAX);
+	}
+
+	#[test]
+	fn to_currency_is_roughly_the_inverse_of_to_vote() {
+		let total_issuance = (u64::MAX as u128) * 10;
+		let value = total_issuance / 3;
+		let vote = SaturatingCurrencyToVote::to_vote(value, total_issuance);
+		let back = SaturatingCurrencyToVote::to_currency(vote, total_issuance);
+		// scaling down and back up loses precision, but should stay within one `factor`.
+		let factor = SaturatingCurrencyToVote::factor(total_issuance);
+		assert!(back.abs_diff(value) < factor);
+	}
+}
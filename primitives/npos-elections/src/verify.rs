@@ -0,0 +1,193 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2020 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Feasibility checking for election solutions that did not come out of one of this crate's own
+//! solvers, e.g. one computed off-chain and submitted to a pallet.
+//!
+//! [`verify_and_score`] re-derives a solution's [`ElectionScore`] the same way
+//! [`crate::evaluate_support`] would, but only after rejecting anything that isn't a well-formed
+//! solution in the first place, without ever running an election algorithm itself.
+
+use crate::{
+	assignment_ratio_to_staked_normalized, build_support_map, evaluate_support, Assignment,
+	CurrencyToVote, ElectionScore, ExtendedBalance, IdentifierT,
+};
+use sp_arithmetic::{traits::Zero, InnerOf, PerThing};
+use sp_std::{collections::btree_set::BTreeSet, prelude::*};
+
+/// Reasons a submitted solution can be rejected by [`verify_and_score`].
+#[derive(Eq, PartialEq, Debug)]
+pub enum FeasibilityError {
+	/// The number of winners does not match the number of desired winners.
+	WrongWinnerCount,
+	/// A voter's distribution backs a target that is not among the winners.
+	NonWinnerBacked,
+	/// A voter's non-empty distribution ratios do not sum to one.
+	RatioSumIncorrect,
+	/// Summing a voter's distribution ratios, or converting the distribution to its staked
+	/// equivalent, overflowed.
+	RatioOverflow,
+	/// A voter's distribution contains the same target more than once.
+	DuplicateTarget,
+	/// A voter's distribution backs itself.
+	SelfVote,
+	/// A winner's final support is below the required `minimum_stake`.
+	InsufficientSupport,
+}
+
+/// Verify that `assignments` is a feasible solution electing exactly `desired_winners` winners
+/// out of `winners`, and if so, return the [`ElectionScore`] it would produce, exactly as
+/// [`crate::evaluate_support`] would compute it directly.
+///
+/// This never re-runs an election algorithm; it only checks the bare minimum structural
+/// invariants that any solution produced by [`crate::seq_phragmen`] or [`crate::phragmms`] already
+/// satisfies by construction, so that a pallet accepting off-chain solutions can reject anything
+/// that doesn't hold cheaply, before doing anything more expensive with it.
+///
+/// `total_issuance` and `stake_of` are converted into the [`VoteWeight`](crate::VoteWeight) domain
+/// via `C`, exactly as [`assignment_ratio_to_staked_normalized`] does, so that a chain whose
+/// issuance exceeds `u64::MAX` scores a submission the same way it would have scored the output of
+/// its own election algorithm.
+pub fn verify_and_score<AccountId, P, Balance, C>(
+	winners: &[AccountId],
+	assignments: &[Assignment<AccountId, P>],
+	desired_winners: usize,
+	total_issuance: Balance,
+	stake_of: impl Fn(&AccountId) -> Balance,
+	minimum_stake: ExtendedBalance,
+) -> Result<ElectionScore, FeasibilityError>
+where
+	AccountId: IdentifierT,
+	P: PerThing,
+	Balance: Clone,
+	C: CurrencyToVote<Balance>,
+	ExtendedBalance: From<InnerOf<P>>,
+{
+	if winners.len() != desired_winners {
+		return Err(FeasibilityError::WrongWinnerCount)
+	}
+
+	let winners_set = winners.iter().cloned().collect::<BTreeSet<_>>();
+
+	for assignment in assignments {
+		let mut seen_targets = BTreeSet::new();
+		let mut accumulated: ExtendedBalance = Zero::zero();
+
+		for (target, ratio) in assignment.distribution.iter() {
+			if target == &assignment.who {
+				return Err(FeasibilityError::SelfVote)
+			}
+			if !seen_targets.insert(target.clone()) {
+				return Err(FeasibilityError::DuplicateTarget)
+			}
+			if !winners_set.contains(target) {
+				return Err(FeasibilityError::NonWinnerBacked)
+			}
+			accumulated = accumulated
+				.checked_add(ExtendedBalance::from(ratio.deconstruct()))
+				.ok_or(FeasibilityError::RatioOverflow)?;
+		}
+
+		// a voter that backs none of the winners has an empty distribution, as the solvers of
+		// this crate emit for it.
+		if !assignment.distribution.is_empty() && accumulated != ExtendedBalance::from(P::ACCURACY) {
+			return Err(FeasibilityError::RatioSumIncorrect)
+		}
+	}
+
+	let staked_assignments = assignment_ratio_to_staked_normalized::<_, _, _, C>(
+		assignments.to_vec(),
+		total_issuance,
+		stake_of,
+	)
+	.map_err(|_| FeasibilityError::RatioOverflow)?;
+
+	let (supports, _) = build_support_map(winners, &staked_assignments);
+
+	if supports.values().any(|support| support.total < minimum_stake) {
+		return Err(FeasibilityError::InsufficientSupport)
+	}
+
+	Ok(evaluate_support(&supports))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::SaturatingCurrencyToVote;
+	use sp_arithmetic::Perbill;
+
+	fn clean_assignments() -> (Vec<u32>, Vec<Assignment<u32, Perbill>>) {
+		let winners = vec![1u32, 2];
+		let assignments = vec![Assignment {
+			who: 10u32,
+			distribution: vec![(1, Perbill::from_percent(50)), (2, Perbill::from_percent(50))],
+		}];
+		(winners, assignments)
+	}
+
+	#[test]
+	fn accepts_a_clean_solution_and_matches_evaluate_support() {
+		let (winners, assignments) = clean_assignments();
+
+		let verified = verify_and_score::<_, _, _, SaturatingCurrencyToVote>(
+			&winners,
+			&assignments,
+			2,
+			1_000u128,
+			|_| 100u128,
+			0,
+		);
+
+		// voter 10's stake of 100 splits evenly, so each winner ends up backed by 50.
+		assert_eq!(verified, Ok([50, 100, 5_000]));
+	}
+
+	#[test]
+	fn rejects_wrong_winner_count() {
+		let (winners, assignments) = clean_assignments();
+
+		let verified = verify_and_score::<_, _, _, SaturatingCurrencyToVote>(
+			&winners,
+			&assignments,
+			1,
+			1_000u128,
+			|_| 100u128,
+			0,
+		);
+
+		assert_eq!(verified, Err(FeasibilityError::WrongWinnerCount));
+	}
+
+	#[test]
+	fn rejects_self_vote() {
+		let (winners, _) = clean_assignments();
+		let assignments =
+			vec![Assignment { who: 1u32, distribution: vec![(1, Perbill::from_percent(100))] }];
+
+		let verified = verify_and_score::<_, _, _, SaturatingCurrencyToVote>(
+			&winners,
+			&assignments,
+			2,
+			1_000u128,
+			|_| 100u128,
+			0,
+		);
+
+		assert_eq!(verified, Err(FeasibilityError::SelfVote));
+	}
+}